@@ -0,0 +1,149 @@
+//! An incremental builder that folds appended leaves into full roots.
+
+use crate::{parent, Index};
+use alloc::vec::Vec;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Slot {
+  level: Index,
+  index: Index,
+}
+
+/// Incrementally folds leaves into [`Roots`] as they're appended, so callers
+/// can know which roots exist after N appends without recomputing
+/// [`full_roots`](crate::full_roots) from scratch every time.
+///
+/// Leaves must be appended in order, starting at flat index `0` and
+/// increasing by `2` each time (`0`, `2`, `4`, ...). Folding a new node only
+/// looks at whatever is already parked at its level, so it's only correct
+/// when consecutive appends are actually siblings — [`append`](Self::append)
+/// enforces that by tracking the next leaf it expects and panicking if the
+/// caller skips or reorders one.
+///
+/// ## Examples
+/// ```rust
+/// use flat_tree::Builder;
+///
+/// let mut builder = Builder::new();
+/// for leaf in [0, 2, 4, 6] {
+///   builder.append(leaf);
+/// }
+/// assert_eq!(builder.finalize().indices(), vec![3]);
+/// ```
+#[derive(Debug, Default, Clone)]
+pub struct Builder {
+  pending: Vec<Option<Slot>>,
+  next_leaf: Index,
+}
+
+impl Builder {
+  /// Creates an empty `Builder`.
+  pub fn new() -> Self {
+    Builder {
+      pending: Vec::new(),
+      next_leaf: 0,
+    }
+  }
+
+  /// Appends the next leaf, folding it (and any now-complete ancestors) into
+  /// the pending roots.
+  ///
+  /// Panics if `leaf` isn't the next leaf in order — leaves must be appended
+  /// starting at `0` and increasing by `2` each time, since folding is only
+  /// correct when consecutive appends are siblings.
+  pub fn append(&mut self, leaf: Index) {
+    assert_eq!(
+      leaf, self.next_leaf,
+      "Builder::append requires leaves in order starting at 0; expected {} but got {}",
+      self.next_leaf, leaf
+    );
+    self.next_leaf += 2;
+
+    self.collect(Slot {
+      level: 0,
+      index: leaf,
+    });
+  }
+
+  fn collect(&mut self, mut node: Slot) {
+    // `Index` is `usize` under the default feature set, making this cast a
+    // no-op there, but it's load-bearing for the `u64`/`u128` features.
+    #[allow(clippy::unnecessary_cast)]
+    let mut level = node.level as usize;
+    loop {
+      if level >= self.pending.len() {
+        self.pending.push(None);
+      }
+      if self.pending[level].take().is_some() {
+        node = Self::up(node);
+        level += 1;
+      } else {
+        self.pending[level] = Some(node);
+        break;
+      }
+    }
+  }
+
+  fn up(node: Slot) -> Slot {
+    Slot {
+      level: node.level + 1,
+      index: parent(node.index),
+    }
+  }
+
+  /// Finalizes the builder, returning the surviving root indices together
+  /// with their levels.
+  pub fn finalize(&self) -> Roots {
+    let nodes = self
+      .pending
+      .iter()
+      .rev()
+      .filter_map(|slot| slot.map(|slot| (slot.index, slot.level)))
+      .collect();
+    Roots { nodes }
+  }
+}
+
+/// The surviving full-root indices of a [`Builder`], together with their
+/// levels, as returned by [`Builder::finalize`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Roots {
+  nodes: Vec<(Index, Index)>,
+}
+
+impl Roots {
+  /// Returns the root indices, ordered from the highest level to the lowest
+  /// (the same left-to-right order as [`full_roots`](crate::full_roots)).
+  pub fn indices(&self) -> Vec<Index> {
+    self.nodes.iter().map(|(index, _)| *index).collect()
+  }
+
+  /// Returns the `(index, level)` pairs for each surviving root.
+  pub fn levels(&self) -> &[(Index, Index)] {
+    &self.nodes
+  }
+}
+
+#[test]
+fn test_builder_matches_full_roots() {
+  let mut builder = Builder::new();
+  for leaf in [0, 2, 4, 6, 8, 10, 12] {
+    builder.append(leaf);
+  }
+  assert_eq!(builder.finalize().indices(), crate::full_roots(14));
+}
+
+#[test]
+fn test_builder_empty() {
+  let builder = Builder::new();
+  assert!(builder.finalize().indices().is_empty());
+}
+
+#[test]
+#[should_panic(expected = "Builder::append requires leaves in order starting at 0")]
+fn test_builder_rejects_out_of_order_leaves() {
+  let mut builder = Builder::new();
+  for leaf in [8, 10, 12, 14] {
+    builder.append(leaf);
+  }
+}
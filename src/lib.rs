@@ -11,11 +11,66 @@
 //! This module exposes a series of functions to help you build and maintain
 //! this data structure.
 //!
+//! ## `no_std`
+//! This crate is `#![no_std]` and all of its indexing helpers are `const fn`,
+//! so they can be used in const contexts and on embedded targets. The integer
+//! type used for indices is [`Index`], which defaults to `usize` but can be
+//! widened with the `u64` or `u128` Cargo features for trees whose indices
+//! routinely exceed what a 32-bit platform's `usize` can hold.
+//!
+//! ## Tracking node presence
+//! [`TreeIndex`] builds on the index math above to track which nodes of a
+//! flat-tree are present, and to generate and verify Merkle proofs against
+//! the resulting full roots.
+//!
+//! ## Streaming full roots
+//! [`Builder`] folds leaves into full roots incrementally as they're
+//! appended, an O(1)-amortized alternative to recomputing [`full_roots`]
+//! from scratch after every append.
+//!
 //! ## See Also
 //! - [mafintosh/merkle-tree-stream (JavaScript)](https://github.com/mafintosh/merkle-tree-stream)
 
+#![no_std]
 #![deny(missing_docs)]
 
+extern crate alloc;
+
+use alloc::vec::Vec;
+
+mod builder;
+mod tree_index;
+
+pub use builder::{Builder, Roots};
+pub use tree_index::{Proof, TreeIndex};
+
+#[cfg(all(feature = "u64", feature = "u128"))]
+compile_error!("features `u64` and `u128` are mutually exclusive");
+
+/// The integer type used for node indices throughout this crate.
+///
+/// Defaults to `usize`. Enable the `u64` or `u128` Cargo feature to widen it,
+/// for platforms where `usize` is narrower than the indices you need to
+/// represent.
+#[cfg(feature = "u128")]
+pub type Index = u128;
+
+/// The integer type used for node indices throughout this crate.
+///
+/// Defaults to `usize`. Enable the `u64` or `u128` Cargo feature to widen it,
+/// for platforms where `usize` is narrower than the indices you need to
+/// represent.
+#[cfg(all(feature = "u64", not(feature = "u128")))]
+pub type Index = u64;
+
+/// The integer type used for node indices throughout this crate.
+///
+/// Defaults to `usize`. Enable the `u64` or `u128` Cargo feature to widen it,
+/// for platforms where `usize` is narrower than the indices you need to
+/// represent.
+#[cfg(not(any(feature = "u64", feature = "u128")))]
+pub type Index = usize;
+
 /// Returns the flat-tree of the tree node at the specified depth and offset.
 ///
 /// ## Examples
@@ -30,8 +85,8 @@
 /// assert_eq!(flat_tree::index(3, 0), 7);
 /// assert_eq!(flat_tree::index(3, 1), 23);
 /// ```
-pub fn index(depth: usize, offset: usize) -> usize {
-  (offset << depth + 1) | ((1 << depth) - 1)
+pub const fn index(depth: Index, offset: Index) -> Index {
+  (offset << (depth + 1)) | ((1 << depth) - 1)
 }
 
 /// Returns the depth of a node.
@@ -44,20 +99,16 @@ pub fn index(depth: usize, offset: usize) -> usize {
 /// assert_eq!(flat_tree::depth(3), 2);
 /// assert_eq!(flat_tree::depth(4), 0);
 /// ```
-pub fn depth(i: usize) -> usize {
-  let mut depth = 0;
-  let mut i = i;
-  while is_odd(i) {
-    i >>= 1;
-    depth += 1;
-  }
-  depth
+pub const fn depth(i: Index) -> Index {
+  // A node's depth is the number of trailing one-bits in its index, i.e. the
+  // number of trailing zero-bits in its complement.
+  (!i).trailing_zeros() as Index
 }
 
 /// Returns the offset of a node with a depth.
-pub fn offset_with_depth(i: usize, depth: usize) -> usize {
+pub const fn offset_with_depth(i: Index, depth: Index) -> Index {
   if is_even(i) {
-    i / 2
+    i >> 1
   } else {
     i >> (depth + 1)
   }
@@ -73,12 +124,12 @@ pub fn offset_with_depth(i: usize, depth: usize) -> usize {
 /// assert_eq!(flat_tree::offset(3), 0);
 /// assert_eq!(flat_tree::offset(4), 2);
 /// ```
-pub fn offset(i: usize) -> usize {
+pub const fn offset(i: Index) -> Index {
   offset_with_depth(i, depth(i))
 }
 
 /// Returns the parent of a node with a depth.
-pub fn parent_with_depth(i: usize, depth: usize) -> usize {
+pub const fn parent_with_depth(i: Index, depth: Index) -> Index {
   index(depth + 1, offset_with_depth(i, depth) >> 1)
 }
 
@@ -94,12 +145,12 @@ pub fn parent_with_depth(i: usize, depth: usize) -> usize {
 /// assert_eq!(flat_tree::parent(2), 1);
 /// assert_eq!(flat_tree::parent(1), 3);
 /// ```
-pub fn parent(i: usize) -> usize {
+pub const fn parent(i: Index) -> Index {
   parent_with_depth(i, depth(i))
 }
 
 /// Returns the sibling of a node with a depth.
-pub fn sibling_with_depth(i: usize, depth: usize) -> usize {
+pub const fn sibling_with_depth(i: Index, depth: Index) -> Index {
   index(depth, offset(i) ^ 1)
 }
 
@@ -112,32 +163,29 @@ pub fn sibling_with_depth(i: usize, depth: usize) -> usize {
 /// assert_eq!(flat_tree::sibling(1), 5);
 /// assert_eq!(flat_tree::sibling(5), 1);
 /// ```
-pub fn sibling(i: usize) -> usize {
+pub const fn sibling(i: Index) -> Index {
   sibling_with_depth(i, depth(i))
 }
 
 /// Returns the parent's sibling, of a node, with a depth.
-pub fn uncle_with_depth(i: usize, depth: usize) -> usize {
+pub const fn uncle_with_depth(i: Index, depth: Index) -> Index {
   sibling_with_depth(parent_with_depth(i, depth), depth + 1)
 }
 
 /// Returns the parent's sibling, of a node.
-pub fn uncle(i: usize) -> usize {
+pub const fn uncle(i: Index) -> Index {
   uncle_with_depth(i, depth(i))
 }
 
 /// Returns both children of a node, with a depth.
-pub fn children_with_depth(i: usize, depth: usize) -> Option<(usize, usize)> {
+pub const fn children_with_depth(i: Index, depth: Index) -> Option<(Index, Index)> {
   if is_even(i) {
     None
   } else if depth == 0 {
     Some((i, i))
   } else {
     let offset = offset_with_depth(i, depth) * 2;
-    Some((
-      index(depth - 1, offset),
-      index(depth - 1, offset + 1),
-    ))
+    Some((index(depth - 1, offset), index(depth - 1, offset + 1)))
   }
 }
 
@@ -150,22 +198,19 @@ pub fn children_with_depth(i: usize, depth: usize) -> Option<(usize, usize)> {
 /// assert_eq!(flat_tree::children(3), Some((1, 5)));
 /// assert_eq!(flat_tree::children(9), Some((8, 10)));
 /// ```
-pub fn children(i: usize) -> Option<(usize, usize)> {
+pub const fn children(i: Index) -> Option<(Index, Index)> {
   children_with_depth(i, depth(i))
 }
 
 /// Returns only the left child of a node, with a depth
 // TODO: handle errors
-pub fn left_child_with_depth(i: usize, depth: usize) -> Option<usize> {
+pub const fn left_child_with_depth(i: Index, depth: Index) -> Option<Index> {
   if is_even(i) {
     None
   } else if depth == 0 {
     Some(i)
   } else {
-    Some(index(
-      depth - 1,
-      offset_with_depth(i, depth) << 1,
-    ))
+    Some(index(depth - 1, offset_with_depth(i, depth) << 1))
   }
 }
 
@@ -177,21 +222,18 @@ pub fn left_child_with_depth(i: usize, depth: usize) -> Option<usize> {
 /// assert_eq!(flat_tree::left_child(1), Some(0));
 /// assert_eq!(flat_tree::left_child(3), Some(1));
 /// ```
-pub fn left_child(i: usize) -> Option<usize> {
+pub const fn left_child(i: Index) -> Option<Index> {
   left_child_with_depth(i, depth(i))
 }
 
 /// Returns only the left child of a node, with a depth.
-pub fn right_child_with_depth(i: usize, depth: usize) -> Option<usize> {
+pub const fn right_child_with_depth(i: Index, depth: Index) -> Option<Index> {
   if is_even(i) {
     None
   } else if depth == 0 {
     Some(i)
   } else {
-    Some(index(
-      depth - 1,
-      (offset_with_depth(i, depth) << 1) + 1,
-    ))
+    Some(index(depth - 1, (offset_with_depth(i, depth) << 1) + 1))
   }
 }
 
@@ -204,12 +246,12 @@ pub fn right_child_with_depth(i: usize, depth: usize) -> Option<usize> {
 /// assert_eq!(flat_tree::right_child(3), Some(5));
 /// ```
 // TODO: handle errors
-pub fn right_child(i: usize) -> Option<usize> {
+pub const fn right_child(i: Index) -> Option<Index> {
   right_child_with_depth(i, depth(i))
 }
 
 /// Returns the right most node in the tree that the node spans, with a depth.
-pub fn right_span_with_depth(i: usize, depth: usize) -> usize {
+pub const fn right_span_with_depth(i: Index, depth: Index) -> Index {
   if depth == 0 {
     i
   } else {
@@ -227,12 +269,12 @@ pub fn right_span_with_depth(i: usize, depth: usize) -> usize {
 /// assert_eq!(flat_tree::right_span(23), 30);
 /// assert_eq!(flat_tree::right_span(27), 30);
 /// ```
-pub fn right_span(i: usize) -> usize {
+pub const fn right_span(i: Index) -> Index {
   right_span_with_depth(i, depth(i))
 }
 
 /// Returns the left most node in the tree that the node spans, with a depth.
-pub fn left_span_with_depth(i: usize, depth: usize) -> usize {
+pub const fn left_span_with_depth(i: Index, depth: Index) -> Index {
   if depth == 0 {
     i
   } else {
@@ -250,13 +292,13 @@ pub fn left_span_with_depth(i: usize, depth: usize) -> usize {
 /// assert_eq!(flat_tree::left_span(23), 16);
 /// assert_eq!(flat_tree::left_span(27), 24);
 /// ```
-pub fn left_span(i: usize) -> usize {
+pub const fn left_span(i: Index) -> Index {
   left_span_with_depth(i, depth(i))
 }
 
 /// Returns the left and right most nodes in the tree that the node spans, with
 /// a depth.
-pub fn spans_with_depth(i: usize, depth: usize) -> (usize, usize) {
+pub const fn spans_with_depth(i: Index, depth: Index) -> (Index, Index) {
   (
     left_span_with_depth(i, depth),
     right_span_with_depth(i, depth),
@@ -273,12 +315,12 @@ pub fn spans_with_depth(i: usize, depth: usize) -> (usize, usize) {
 /// assert_eq!(flat_tree::spans(23), (16, 30));
 /// assert_eq!(flat_tree::spans(27), (24, 30));
 /// ```
-pub fn spans(i: usize) -> (usize, usize) {
+pub const fn spans(i: Index) -> (Index, Index) {
   spans_with_depth(i, depth(i))
 }
 
 /// Returns how many nodes are in the tree that the node spans, with a depth.
-pub fn count_with_depth(_: usize, depth: usize) -> usize {
+pub const fn count_with_depth(_: Index, depth: Index) -> Index {
   (2 << depth) - 1
 }
 
@@ -293,10 +335,144 @@ pub fn count_with_depth(_: usize, depth: usize) -> usize {
 /// assert_eq!(flat_tree::count(23), 15);
 /// assert_eq!(flat_tree::count(27), 7);
 /// ```
-pub fn count(i: usize) -> usize {
+pub const fn count(i: Index) -> Index {
   count_with_depth(i, depth(i))
 }
 
+/// Returns the difference in depth between `b` and `a` (`depth(b) - depth(a)`),
+/// or `None` if `a` is deeper than `b`.
+///
+/// Only the depths are compared: whether the subtree rooted at `b` actually
+/// contains `a` is not checked (see [`is_in_subtree`] for that).
+///
+/// ## Examples
+/// ```rust
+/// assert_eq!(flat_tree::relative_depth(0, 3), Some(2));
+/// assert_eq!(flat_tree::relative_depth(2, 3), Some(2));
+/// assert_eq!(flat_tree::relative_depth(3, 3), Some(0));
+/// assert_eq!(flat_tree::relative_depth(3, 0), None);
+/// ```
+pub const fn relative_depth(a: Index, b: Index) -> Option<Index> {
+  // A node's depth is the log2 of the power-of-two span it roots, so the
+  // relative depth falls out of the difference between those two spans.
+  // `b` can only be an ancestor of `a` if `b`'s span is at least as large.
+  let da = depth(a) + 1;
+  let db = depth(b) + 1;
+  if da > db {
+    None
+  } else {
+    Some(db - da)
+  }
+}
+
+/// Returns `true` if `index` lies within the subtree rooted at `root`.
+///
+/// ## Examples
+/// ```rust
+/// assert!(flat_tree::is_in_subtree(3, 0));
+/// assert!(flat_tree::is_in_subtree(3, 6));
+/// assert!(!flat_tree::is_in_subtree(3, 8));
+/// ```
+pub const fn is_in_subtree(root: Index, index: Index) -> bool {
+  if relative_depth(index, root).is_none() {
+    return false;
+  }
+
+  let left = left_span(root);
+  let right = right_span(root);
+  index >= left && index <= right
+}
+
+/// Returns the first (left-most) leaf of the subtree rooted at `root`, given
+/// its depth.
+///
+/// ## Examples
+/// ```rust
+/// assert_eq!(flat_tree::first_leaf(3, 2), 0);
+/// assert_eq!(flat_tree::first_leaf(11, 2), 8);
+/// ```
+pub const fn first_leaf(root: Index, depth: Index) -> Index {
+  left_span_with_depth(root, depth)
+}
+
+/// Returns the last (right-most) leaf of the subtree rooted at `root`, given
+/// its depth.
+///
+/// ## Examples
+/// ```rust
+/// assert_eq!(flat_tree::last_leaf(3, 2), 6);
+/// assert_eq!(flat_tree::last_leaf(11, 2), 14);
+/// ```
+pub const fn last_leaf(root: Index, depth: Index) -> Index {
+  right_span_with_depth(root, depth)
+}
+
+/// Returns the flat-tree index that roots the `offset`-th subtree of height
+/// `level`.
+///
+/// This is [`index`] under a name that matches how callers building partial
+/// proofs think about the tree: "the subtree root at this level and offset".
+///
+/// ## Examples
+/// ```rust
+/// assert_eq!(flat_tree::subtree_root(0, 0), 0);
+/// assert_eq!(flat_tree::subtree_root(1, 0), 1);
+/// assert_eq!(flat_tree::subtree_root(2, 1), 11);
+/// ```
+pub const fn subtree_root(level: Index, offset: Index) -> Index {
+  index(level, offset)
+}
+
+/// Returns the `offset`-th descendant subtree root of `i` at `level`.
+///
+/// `i` spans `2^(depth(i) - level)` equal sub-ranges at `level`; this is the
+/// inverse of [`subtree_root`], letting callers name one of those
+/// descendants directly instead of descending from `i` child-by-child.
+///
+/// ## Examples
+/// ```rust
+/// assert_eq!(flat_tree::descendant_root(7, 1, 0), 1);
+/// assert_eq!(flat_tree::descendant_root(7, 1, 1), 5);
+/// assert_eq!(flat_tree::descendant_root(7, 1, 2), 9);
+/// assert_eq!(flat_tree::descendant_root(7, 0, 3), 6);
+/// ```
+pub const fn descendant_root(i: Index, level: Index, offset: Index) -> Index {
+  let base = left_span(i) >> (level + 1);
+  index(level, base + offset)
+}
+
+/// The maximum number of full roots any node can have.
+///
+/// A node can have at most one full root per bit of [`Index`], so this
+/// tracks `Index::BITS` rather than a fixed constant — the `u64`/`u128`
+/// features widen `Index` and widen this right along with it. This is the
+/// capacity [`full_roots`] pre-allocates, and the fixed size of
+/// the buffer [`full_roots_into_slice`] writes into.
+pub const MAX_FULL_ROOTS: usize = Index::BITS as usize;
+
+fn for_each_full_root(i: Index, mut push: impl FnMut(Index)) {
+  if is_odd(i) {
+    return;
+  }
+
+  let mut tmp = i >> 1;
+  let mut offset = 0;
+  let mut factor = 1;
+
+  loop {
+    if tmp == 0 {
+      break;
+    }
+    while factor * 2 <= tmp {
+      factor *= 2;
+    }
+    push(offset + factor - 1);
+    offset += 2 * factor;
+    tmp -= factor;
+    factor = 1;
+  }
+}
+
 /// Returns all the previous fully rooted trees before the node.
 ///
 /// ## Examples
@@ -308,55 +484,82 @@ pub fn count(i: usize) -> usize {
 /// assert_eq!(flat_tree::full_roots(18), [7, 16]);
 /// assert_eq!(flat_tree::full_roots(16), [7]);
 /// ```
-pub fn full_roots(i: usize) -> Vec<usize> {
-  let mut result = Vec::with_capacity(64);
+pub fn full_roots(i: Index) -> Vec<Index> {
+  let mut result = Vec::with_capacity(MAX_FULL_ROOTS);
+  full_roots_into(i, &mut result);
+  result
+}
 
-  if is_odd(i) {
-    result
-  } else {
-    let mut tmp = i >> 1;
-    let mut offset = 0;
-    let mut factor = 1;
-
-    loop {
-      if tmp == 0 {
-        break;
-      }
-      while factor * 2 <= tmp {
-        factor *= 2;
-      }
-      result.push(offset + factor - 1);
-      offset += 2 * factor;
-      tmp -= factor;
-      factor = 1;
-    }
+/// Writes all the previous fully rooted trees before the node into `out`,
+/// without allocating a fresh `Vec`.
+///
+/// Roots are appended to whatever `out` already holds; callers that want
+/// only this call's roots should `out.clear()` first. Useful for hot loops
+/// that call this once per appended block and would otherwise allocate a
+/// new `Vec` every time.
+///
+/// ## Examples
+/// ```rust
+/// let mut roots = Vec::with_capacity(flat_tree::MAX_FULL_ROOTS);
+/// flat_tree::full_roots_into(20, &mut roots);
+/// assert_eq!(roots, [7, 17]);
+/// ```
+pub fn full_roots_into(i: Index, out: &mut Vec<Index>) {
+  for_each_full_root(i, |root| out.push(root));
+}
 
-    result
-  }
+/// Writes all the previous fully rooted trees before the node into the
+/// fixed-size `out` buffer, without allocating at all, and returns the
+/// number of roots written.
+///
+/// ## Examples
+/// ```rust
+/// let mut roots = [0; flat_tree::MAX_FULL_ROOTS];
+/// let n = flat_tree::full_roots_into_slice(20, &mut roots);
+/// assert_eq!(&roots[..n], [7, 17]);
+/// ```
+pub fn full_roots_into_slice(i: Index, out: &mut [Index; MAX_FULL_ROOTS]) -> usize {
+  let mut count = 0;
+  for_each_full_root(i, |root| {
+    out[count] = root;
+    count += 1;
+  });
+  count
+}
+
+#[test]
+#[cfg(feature = "u128")]
+fn test_full_roots_into_slice_fits_wide_indices() {
+  // A node whose full-roots chain needs more than 64 entries, only
+  // reachable once `Index` is widened past `u64`.
+  let i: Index = ((1u128 << 100) - 2) & !1;
+  let mut roots = [0; MAX_FULL_ROOTS];
+  let n = full_roots_into_slice(i, &mut roots);
+  assert_eq!(&roots[..n], full_roots(i).as_slice());
 }
 
 #[inline(always)]
-fn is_even(num: usize) -> bool {
+pub(crate) const fn is_even(num: Index) -> bool {
   (num & 1) == 0
 }
 #[test]
 fn test_is_even() {
-  assert_eq!(is_even(0), true);
-  assert_eq!(is_even(1), false);
-  assert_eq!(is_even(2), true);
-  assert_eq!(is_even(3), false);
+  assert!(is_even(0));
+  assert!(!is_even(1));
+  assert!(is_even(2));
+  assert!(!is_even(3));
 }
 
 #[inline(always)]
-fn is_odd(num: usize) -> bool {
+const fn is_odd(num: Index) -> bool {
   (num & 1) != 0
 }
 #[test]
 fn test_is_odd() {
-  assert_eq!(is_odd(0), false);
-  assert_eq!(is_odd(1), true);
-  assert_eq!(is_odd(2), false);
-  assert_eq!(is_odd(3), true);
+  assert!(!is_odd(0));
+  assert!(is_odd(1));
+  assert!(!is_odd(2));
+  assert!(is_odd(3));
 }
 
 #[test]
@@ -376,3 +579,9 @@ fn test_child_to_parent_to_child() {
   }
   assert_eq!(child, 0);
 }
+
+#[test]
+fn test_index_is_const_evaluable() {
+  const ROOT: Index = index(1, 0);
+  assert_eq!(ROOT, 1);
+}
@@ -0,0 +1,246 @@
+//! A bitfield-backed index of which flat-tree nodes are present.
+
+use crate::{children, full_roots, is_even, offset, parent, sibling, Index};
+use alloc::vec::Vec;
+
+const BITS_PER_WORD: usize = 64;
+
+// `Index` is `usize` under the default feature set, making this conversion a
+// no-op there, but it's load-bearing for the `u64`/`u128` features, where it
+// guards against silently truncating an index that doesn't fit in `usize`.
+#[allow(clippy::useless_conversion)]
+fn word_and_bit(index: Index) -> (usize, u32) {
+  let i = usize::try_from(index)
+    .expect("flat-tree index does not fit in this platform's usize, can't address a bit for it");
+  (i / BITS_PER_WORD, (i % BITS_PER_WORD) as u32)
+}
+
+/// Tracks which nodes of a flat-tree are present.
+///
+/// Setting a leaf also marks every ancestor whose two children are both
+/// present, stopping at the topmost fully-populated node. This lets you ask
+/// which roots are fully rooted, and build proofs that let a peer verify a
+/// node against those roots without re-implementing the upward walk.
+///
+/// ## Examples
+/// ```rust
+/// use flat_tree::TreeIndex;
+///
+/// let mut index = TreeIndex::new();
+/// index.set(0);
+/// index.set(2);
+/// assert!(index.get(0));
+/// assert!(index.get(2));
+/// assert!(index.get(1)); // both children of 1 are present
+/// ```
+#[derive(Debug, Default, Clone)]
+pub struct TreeIndex {
+  bits: Vec<u64>,
+  leaves: Index,
+}
+
+impl TreeIndex {
+  /// Creates an empty `TreeIndex`.
+  pub fn new() -> Self {
+    TreeIndex {
+      bits: Vec::new(),
+      leaves: 0,
+    }
+  }
+
+  fn get_bit(&self, index: Index) -> bool {
+    let (word, bit) = word_and_bit(index);
+    self.bits.get(word).is_some_and(|w| (w >> bit) & 1 == 1)
+  }
+
+  fn set_bit(&mut self, index: Index) {
+    let (word, bit) = word_and_bit(index);
+    if word >= self.bits.len() {
+      self.bits.resize(word + 1, 0);
+    }
+    self.bits[word] |= 1 << bit;
+  }
+
+  /// Returns `true` if `index` is recorded as present.
+  pub fn get(&self, index: Index) -> bool {
+    self.get_bit(index)
+  }
+
+  /// Marks `index` (a leaf) as present, then walks upward via [`parent`],
+  /// marking a parent only once both of its children are present.
+  pub fn set(&mut self, index: Index) {
+    self.set_bit(index);
+
+    if is_even(index) {
+      let leaf_no = offset(index) + 1;
+      if leaf_no > self.leaves {
+        self.leaves = leaf_no;
+      }
+    }
+
+    let mut node = index;
+    loop {
+      let candidate = parent(node);
+      match children(candidate) {
+        Some((left, right)) if self.get_bit(left) && self.get_bit(right) => {
+          self.set_bit(candidate);
+          node = candidate;
+        }
+        _ => break,
+      }
+    }
+  }
+
+  /// Returns the full roots of the nodes recorded so far.
+  ///
+  /// `self.leaves` only tracks the highest leaf offset ever [`set`](Self::set),
+  /// so the structurally-possible roots it implies are filtered down to the
+  /// ones actually backed by set bits — a leaf skipped earlier in the range
+  /// (e.g. `set(4)` without `set(0)`/`set(2)`) keeps its would-be parent out
+  /// of the result.
+  pub fn full_roots(&self) -> Vec<Index> {
+    full_roots(2 * self.leaves)
+      .into_iter()
+      .filter(|&root| self.get_bit(root))
+      .collect()
+  }
+
+  /// Builds a [`Proof`] for `index`: the sibling/uncle chain needed to
+  /// reconstruct one of `full_roots` from `index`.
+  ///
+  /// Returns `None` if `index` isn't recorded as present, or if the upward
+  /// walk from it reaches a node that isn't recorded as present before
+  /// reaching one of `full_roots` — i.e. `index` doesn't actually sit under a
+  /// fully-recorded root.
+  pub fn proof(&self, index: Index) -> Option<Proof> {
+    if !self.get_bit(index) {
+      return None;
+    }
+
+    let full_roots = self.full_roots();
+    let mut nodes = Vec::new();
+    let mut node = index;
+
+    while !full_roots.contains(&node) {
+      if node >= 2 * self.leaves {
+        return None;
+      }
+      let uncle = sibling(node);
+      if !self.get_bit(uncle) {
+        return None;
+      }
+      nodes.push(uncle);
+      node = parent(node);
+    }
+
+    Some(Proof {
+      index,
+      nodes,
+      full_roots,
+    })
+  }
+
+  /// Verifies that `nodes` is the sibling/uncle chain that reconstructs a
+  /// full root from `index`, returning the full roots required alongside it
+  /// if so.
+  ///
+  /// This only succeeds if `index` and every sibling it claims are actually
+  /// recorded as present — matching a caller-supplied chain arithmetically
+  /// isn't enough, since that chain might not correspond to anything this
+  /// index has actually seen.
+  pub fn verify(&self, index: Index, nodes: &[Index]) -> Option<Vec<Index>> {
+    if !self.get_bit(index) {
+      return None;
+    }
+
+    let full_roots = self.full_roots();
+    let mut node = index;
+    let mut consumed = 0;
+
+    while !full_roots.contains(&node) {
+      if node >= 2 * self.leaves {
+        return None;
+      }
+      let uncle = sibling(node);
+      if !self.get_bit(uncle) || nodes.get(consumed) != Some(&uncle) {
+        return None;
+      }
+      node = parent(node);
+      consumed += 1;
+    }
+
+    if consumed == nodes.len() {
+      Some(full_roots)
+    } else {
+      None
+    }
+  }
+}
+
+/// A proof of the sibling/uncle chain needed to reconstruct a full root from
+/// a node, as produced by [`TreeIndex::proof`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Proof {
+  /// The node the proof was generated for.
+  pub index: Index,
+  /// The sibling/uncle chain from `index` up to (but not including) the full
+  /// root that contains it.
+  pub nodes: Vec<Index>,
+  /// The full roots of the tree at the time the proof was generated.
+  pub full_roots: Vec<Index>,
+}
+
+#[test]
+fn test_set_marks_parents_once_complete() {
+  let mut index = TreeIndex::new();
+  assert!(!index.get(1));
+  index.set(0);
+  assert!(!index.get(1));
+  index.set(2);
+  assert!(index.get(1));
+}
+
+#[test]
+fn test_full_roots_tracks_leaves() {
+  let mut index = TreeIndex::new();
+  for leaf in [0, 2, 4, 6] {
+    index.set(leaf);
+  }
+  assert_eq!(index.full_roots(), alloc::vec![3]);
+}
+
+#[test]
+fn test_proof_round_trips_through_verify() {
+  let mut index = TreeIndex::new();
+  for leaf in [0, 2, 4] {
+    index.set(leaf);
+  }
+  let proof = index.proof(0).expect("0 is under a full root");
+  assert_eq!(index.verify(0, &proof.nodes), Some(proof.full_roots));
+}
+
+#[test]
+fn test_proof_of_unrooted_index_returns_none() {
+  let index = TreeIndex::new();
+  assert_eq!(index.proof(0), None);
+}
+
+#[test]
+fn test_verify_rejects_chain_over_unset_nodes() {
+  let mut index = TreeIndex::new();
+  index.set(4); // leaves 0 and 2 are skipped entirely
+  assert!(!index.get(0));
+  assert!(!index.get(2));
+  assert!(!index.get(1));
+
+  assert_eq!(index.full_roots(), alloc::vec![4]);
+  assert_eq!(index.verify(0, &[2]), None);
+}
+
+#[test]
+#[cfg(feature = "u128")]
+#[should_panic(expected = "does not fit in this platform's usize")]
+fn test_index_wider_than_usize_is_rejected_not_truncated() {
+  let mut index = TreeIndex::new();
+  index.set((usize::MAX as Index) + 3);
+}